@@ -1,14 +1,35 @@
 //! A module to encapsulate the functionality of the [`App`] struct
 
-use super::{HandlerList, RequestHandler};
-use regex::Regex;
+use super::middleware::Middleware;
+use super::{Handler, HandlerList, Params, PathMatcher};
+use http::Method;
 use std::io::prelude::*;
 use std::net::{TcpListener, TcpStream};
 use std::ops::Deref;
-/// Manages [`RequestHandler`] functions and the state of your web service
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Read timeout applied to a connection when [`App::read_timeout`] hasn't been called
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of worker threads to use when `SIMPLE_HTTP_WORKERS` isn't set: the available
+/// parallelism, or a single worker if that can't be determined
+fn default_worker_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// A request read off a connection, paired with whatever bytes were read past the end of its
+/// body and must be carried into the next [`App::read_request`] call on the same connection
+type ReadResult = std::io::Result<(Option<http::Request<Vec<u8>>>, Vec<u8>)>;
+
+/// Manages [`Handler`]s and the state of your web service
 pub struct App {
     listener: TcpListener,
     handlers: HandlerList,
+    middleware: Vec<Box<dyn Middleware>>,
+    workers: usize,
+    read_timeout: Duration,
 }
 
 impl App {
@@ -17,17 +38,63 @@ impl App {
         App {
             listener: TcpListener::bind(ip).unwrap(),
             handlers: HandlerList::new(),
+            middleware: Vec::new(),
+            workers: std::env::var("SIMPLE_HTTP_WORKERS")
+                .ok()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(default_worker_count),
+            read_timeout: DEFAULT_READ_TIMEOUT,
         }
     }
-    /// Start the [`App`] instance and start listening for incoming connections
-    pub fn run(&self) {
-        for stream in self.listener.incoming() {
-            let stream = stream.unwrap();
+    /// Override the number of worker threads `run` spawns to handle connections concurrently
+    pub fn workers(&mut self, n: usize) {
+        self.workers = n;
+    }
+    /// Override how long a connection may sit idle waiting for a complete request before it's
+    /// sent a `408 Request Timeout` and closed
+    pub fn read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = timeout;
+    }
+    /// Start the [`App`] instance: spawn [`Self::workers`] worker threads and hand each accepted
+    /// connection to one of them over a channel, so a slow handler no longer stalls every client
+    pub fn run(self) {
+        let handlers = Arc::new(self.handlers);
+        let middleware = Arc::new(self.middleware);
+        let read_timeout = self.read_timeout;
+        let (tx, rx) = mpsc::channel::<TcpStream>();
+        let rx = Arc::new(Mutex::new(rx));
 
-            self.handle_connection(stream);
+        for _ in 0..self.workers {
+            let handlers = Arc::clone(&handlers);
+            let middleware = Arc::clone(&middleware);
+            let rx = Arc::clone(&rx);
+            thread::spawn(move || loop {
+                let stream = rx.lock().unwrap().recv();
+                match stream {
+                    Ok(stream) => {
+                        Self::handle_connection(&handlers, &middleware, read_timeout, stream);
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            if tx.send(stream).is_err() {
+                break;
+            }
         }
     }
-    /// Add a [`RequestHandler`] that handles requests to a path matching `path`
+    /// Register a [`Middleware`] to run around every request, in registration order for
+    /// `before` and reverse registration order for `after`
+    pub fn add_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Box::new(middleware));
+    }
+    /// Add a [`Handler`] that handles requests to a path matching `path`
     ///
     /// Path matching is done via regular expressions: a `path` of `"/(foo|bar)"` would match both `/foo` and `/bar`.
     /// Take note that matching is done lazily in order of creation: less specific patterns should be added after
@@ -43,40 +110,215 @@ impl App {
     /// }
     ///
     /// // Responds to request with host uri
-    /// fn echo_handler(req: http::Request<&str>) -> http::Response<String> {
+    /// fn echo_handler(req: &http::Request<Vec<u8>>, _params: simple_http::Params) -> http::Response<Vec<u8>> {
     ///     let host = format!("{:?}", req.headers().get("Host").unwrap());
     ///     http::Response::builder()
     ///         .status(200)
-    ///         .body(host)
+    ///         .body(host.into_bytes())
     ///         .unwrap()
     /// }
     /// ```
-    pub fn add_handler(&mut self, path: &str, handler: RequestHandler) {
-        self.handlers.push((
-            super::PathMatcher {
-                regex: Regex::new(path).unwrap(),
-            },
-            handler,
-        ));
+    pub fn add_handler(&mut self, path: &str, handler: impl Handler + 'static) {
+        self.add_route(None, path, handler);
+    }
+    /// Add a [`Handler`] that only handles `GET` requests to a path matching `path`
+    pub fn get(&mut self, path: &str, handler: impl Handler + 'static) {
+        self.add_route(Some(Method::GET), path, handler);
+    }
+    /// Add a [`Handler`] that only handles `POST` requests to a path matching `path`
+    pub fn post(&mut self, path: &str, handler: impl Handler + 'static) {
+        self.add_route(Some(Method::POST), path, handler);
+    }
+    /// Add a [`Handler`] that only handles `PUT` requests to a path matching `path`
+    pub fn put(&mut self, path: &str, handler: impl Handler + 'static) {
+        self.add_route(Some(Method::PUT), path, handler);
+    }
+    /// Add a [`Handler`] that only handles `DELETE` requests to a path matching `path`
+    pub fn delete(&mut self, path: &str, handler: impl Handler + 'static) {
+        self.add_route(Some(Method::DELETE), path, handler);
+    }
+    /// Add a [`Handler`] that only handles `method` requests to a path matching `path`, or any
+    /// method if `method` is `None`
+    pub fn add_route(&mut self, method: Option<Method>, path: &str, handler: impl Handler + 'static) {
+        self.handlers.push((PathMatcher::new(path), method, Box::new(handler)));
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) -> Option<usize> {
-        let mut buffer = [0; 1024];
-        stream.read(&mut buffer).unwrap();
+    /// Percent-decode the named capture groups of `caps` into the [`Params`] for `matcher`
+    fn extract_params(matcher: &PathMatcher, caps: &regex::Captures) -> Params {
+        matcher
+            .param_names
+            .iter()
+            .filter_map(|name| {
+                let value = caps.name(name)?.as_str();
+                let decoded = percent_encoding::percent_decode_str(value).decode_utf8_lossy();
+                Some((name.clone(), decoded.into_owned()))
+            })
+            .collect()
+    }
 
-        let request_string = String::from_utf8_lossy(&buffer[..]);
+    /// Find the [`Handler`] registered for `method` on a path matching `path`.
+    ///
+    /// If no path matches at all, returns `Ok(None)`. If a path matches but none of its
+    /// registered methods do, returns `Err` with the list of methods that *are* registered for
+    /// that path, so the caller can reply `405` with an `Allow` header.
+    fn find_handler<'h>(
+        handlers: &'h HandlerList,
+        method: &Method,
+        path: &str,
+    ) -> Result<Option<(&'h dyn Handler, Params)>, Vec<Method>> {
+        let mut allowed = Vec::new();
+        for (matcher, route_method, handler) in handlers {
+            let caps = match matcher.regex.captures(path) {
+                Some(caps) => caps,
+                None => continue,
+            };
+            match route_method {
+                None => return Ok(Some((handler.as_ref(), Self::extract_params(matcher, &caps)))),
+                Some(m) if m == method => {
+                    return Ok(Some((handler.as_ref(), Self::extract_params(matcher, &caps))))
+                }
+                Some(m) => allowed.push(m.clone()),
+            }
+        }
+        if allowed.is_empty() {
+            Ok(None)
+        } else {
+            Err(allowed)
+        }
+    }
 
-        let req = super::http_util::parse_request(request_string.deref()).unwrap();
-        let matching_path = self
-            .handlers
-            .iter()
-            .find(|r| r.0.regex.is_match(req.uri().path()));
+    /// Read a full request off `stream`: grow a buffer (seeded with `carry`, any bytes already
+    /// read past the end of a *previous* request on this connection) until the `\r\n\r\n` header
+    /// terminator shows up, then read exactly `Content-Length` more bytes (if any) for the body,
+    /// so requests aren't silently truncated by a single fixed-size `read`.
+    ///
+    /// Returns the parsed request alongside whatever bytes were read past the end of its body -
+    /// a pipelining client, or one that simply doesn't wait for the response before sending the
+    /// next request, can have the next request's bytes arrive in the same `read` as the tail of
+    /// this one. The caller must feed that leftover back in as `carry` on its next call instead
+    /// of discarding it, or a kept-alive connection will lose whichever request landed there.
+    ///
+    /// `Ok((None, _))` means the client closed the connection before sending a complete request
+    /// head, or the head it sent could not be parsed; an `Err`
+    /// means the read itself failed, which for a connection with a read timeout set almost
+    /// always means the client dawdled past it.
+    fn read_request(stream: &mut TcpStream, carry: Vec<u8>) -> ReadResult {
+        let mut buffer = carry;
+        let mut chunk = [0; 1024];
 
-        let res = match matching_path {
-            Some((_, h)) => h(req),
-            None => super::default_handlers::not_found(req),
+        let header_end = loop {
+            if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos;
+            }
+            let read = stream.read(&mut chunk)?;
+            if read == 0 {
+                return Ok((None, Vec::new()));
+            }
+            buffer.extend_from_slice(&chunk[..read]);
         };
 
+        let mut body = buffer.split_off(header_end + 4);
+        buffer.truncate(header_end + 2);
+        let head = String::from_utf8_lossy(&buffer).into_owned();
+        let content_length = Self::content_length(&head);
+
+        while body.len() < content_length {
+            let read = stream.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..read]);
+        }
+        let leftover = if body.len() > content_length {
+            body.split_off(content_length)
+        } else {
+            Vec::new()
+        };
+
+        Ok((super::http_util::parse_request(&head, body), leftover))
+    }
+
+    /// Scan parsed request-line-plus-headers text for a `Content-Length` value
+    fn content_length(head: &str) -> usize {
+        head.lines()
+            .find_map(|line| {
+                let (key, value) = line.split_once(": ")?;
+                if key.eq_ignore_ascii_case("Content-Length") {
+                    value.trim().parse::<usize>().ok()
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(0)
+    }
+
+    fn handle_connection(
+        handlers: &HandlerList,
+        middleware: &[Box<dyn Middleware>],
+        read_timeout: Duration,
+        mut stream: TcpStream,
+    ) -> Option<usize> {
+        let _ = stream.set_read_timeout(Some(read_timeout));
+        let mut carry = Vec::new();
+
+        loop {
+            let mut req = match Self::read_request(&mut stream, carry) {
+                Ok((Some(req), leftover)) => {
+                    carry = leftover;
+                    req
+                }
+                Ok((None, _)) => return None,
+                Err(_) => {
+                    return Self::write_response(&mut stream, &super::default_handlers::request_timeout());
+                }
+            };
+
+            let keep_alive = req
+                .headers()
+                .get(http::header::CONNECTION)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("keep-alive"));
+
+            // A panicking handler or middleware must only fail the one request it's handling -
+            // not take down the worker thread, which would silently wedge every other
+            // connection waiting on the now-undrained channel.
+            let dispatched = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                for mw in middleware {
+                    mw.before(&mut req);
+                }
+
+                let matching_path = Self::find_handler(handlers, req.method(), req.uri().path());
+
+                let mut res = match matching_path {
+                    Ok(Some((h, params))) => h.call(&req, params),
+                    Ok(None) => super::default_handlers::not_found(&req),
+                    Err(allowed) => super::default_handlers::method_not_allowed(&req, &allowed),
+                };
+
+                for mw in middleware.iter().rev() {
+                    mw.after(&req, &mut res);
+                }
+
+                res
+            }));
+
+            let res = match dispatched {
+                Ok(res) => res,
+                Err(_) => super::default_handlers::internal_server_error(),
+            };
+
+            let written = Self::write_response(&mut stream, &res);
+            if !keep_alive || written.is_none() {
+                return written;
+            }
+        }
+    }
+
+    /// Serialize `res` onto the wire and flush it, returning the number of bytes written
+    ///
+    /// The body is kept as raw bytes (not a `String`) all the way out so binary responses, like
+    /// [`crate::default_handlers::static_files`]'s, reach the client intact.
+    fn write_response(stream: &mut TcpStream, res: &http::Response<Vec<u8>>) -> Option<usize> {
         let mut header_string = String::new();
 
         res.headers().iter().for_each(|(k, v)| {
@@ -88,18 +330,91 @@ impl App {
             )
         });
 
-        let res_str = format!(
-            "{version:?} {status} {reason}\r\n{headers}\r\n{body}",
+        if !res.headers().contains_key(http::header::CONTENT_LENGTH) {
+            header_string = format!("{}Content-Length: {}\r\n", header_string, res.body().len());
+        }
+
+        let head_str = format!(
+            "{version:?} {status} {reason}\r\n{headers}\r\n",
             version = res.version(),
             status = res.status().as_str(),
             reason = res.status().canonical_reason().unwrap(),
-            headers = header_string,
-            body = res.body()
+            headers = header_string
         );
 
-        match stream.write(res_str.as_bytes()) {
-            Ok(t) => Some(t),
-            Err(_) => None,
+        if stream.write_all(head_str.as_bytes()).is_err()
+            || stream.write_all(res.body()).is_err()
+            || stream.flush().is_err()
+        {
+            return None;
         }
+        Some(head_str.len() + res.body().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_length_parses_the_header_value() {
+        let head = "GET /x HTTP/1.1\r\nHost: h\r\nContent-Length: 12\r\n";
+        assert_eq!(App::content_length(head), 12);
+    }
+
+    #[test]
+    fn content_length_defaults_to_zero_when_absent() {
+        let head = "GET /x HTTP/1.1\r\nHost: h\r\n";
+        assert_eq!(App::content_length(head), 0);
+    }
+
+    #[test]
+    fn content_length_is_case_insensitive() {
+        let head = "GET /x HTTP/1.1\r\ncontent-length: 3\r\n";
+        assert_eq!(App::content_length(head), 3);
+    }
+
+    /// Connects a loopback client/server pair, writes `sent` from the client side, and hands the
+    /// server side of the connection to [`App::read_request`] along with `carry`
+    fn read_request_over_loopback(carry: Vec<u8>, sent: &[u8]) -> ReadResult {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        client.write_all(sent).unwrap();
+        drop(client);
+
+        App::read_request(&mut server, carry)
+    }
+
+    #[test]
+    fn read_request_parses_a_request_with_no_body() {
+        let (req, leftover) =
+            read_request_over_loopback(Vec::new(), b"GET /x HTTP/1.1\r\nHost: h\r\n\r\n").unwrap();
+        let req = req.unwrap();
+        assert_eq!(req.method(), Method::GET);
+        assert_eq!(req.uri().path(), "/x");
+        assert!(leftover.is_empty());
+    }
+
+    #[test]
+    fn read_request_reads_exactly_content_length_bytes_of_body() {
+        let sent = b"POST /x HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\nhello";
+        let (req, leftover) = read_request_over_loopback(Vec::new(), sent).unwrap();
+        assert_eq!(req.unwrap().body(), b"hello");
+        assert!(leftover.is_empty());
+    }
+
+    #[test]
+    fn read_request_carries_pipelined_bytes_into_the_leftover_instead_of_dropping_them() {
+        let sent = b"GET /a HTTP/1.1\r\nHost: h\r\n\r\nGET /b HTTP/1.1\r\nHost: h\r\n\r\n";
+        let (req, leftover) = read_request_over_loopback(Vec::new(), sent).unwrap();
+        assert_eq!(req.unwrap().uri().path(), "/a");
+        assert!(!leftover.is_empty());
+
+        // Feeding the leftover back in as `carry` on the next call, as the keep-alive loop in
+        // `handle_connection` does, recovers the second pipelined request instead of losing it.
+        let (req, _) = read_request_over_loopback(leftover, b"").unwrap();
+        assert_eq!(req.unwrap().uri().path(), "/b");
     }
 }
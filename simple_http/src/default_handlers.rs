@@ -1,9 +1,118 @@
 //! A collection of [`RequestHandler`](super::RequestHandler)s useful for common, simple behaviors like 40x status codes
 
+use super::{Handler, Params};
+use std::path::{Path, PathBuf};
+
 /// Simple catch-all function for returning a `404 Not Found` when no paths match
-pub fn not_found(_req: http::Request<&str>) -> http::Response<String> {
+pub fn not_found(_req: &http::Request<Vec<u8>>) -> http::Response<Vec<u8>> {
     http::Response::builder()
         .status(404)
-        .body(String::new())
+        .body(Vec::new())
+        .unwrap()
+}
+
+/// Returned when a connection's read timeout elapses before a complete request arrives; there's
+/// no parsed [`http::Request`] to hand a handler at that point, so this takes none
+pub fn request_timeout() -> http::Response<Vec<u8>> {
+    http::Response::builder()
+        .status(408)
+        .body(Vec::new())
+        .unwrap()
+}
+
+/// Returned when a [`Handler`](super::Handler) or [`Middleware`](super::middleware::Middleware)
+/// panics, so one misbehaving route fails only the request it was handling instead of taking
+/// down the worker thread serving it
+pub fn internal_server_error() -> http::Response<Vec<u8>> {
+    http::Response::builder()
+        .status(500)
+        .body(Vec::new())
+        .unwrap()
+}
+
+/// Returned when a path matches a registered [`PathMatcher`](super::PathMatcher) but none of
+/// its routes accept the request's method; `allowed` is listed in the response's `Allow` header
+pub fn method_not_allowed(_req: &http::Request<Vec<u8>>, allowed: &[http::Method]) -> http::Response<Vec<u8>> {
+    let allow = allowed
+        .iter()
+        .map(|m| m.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    http::Response::builder()
+        .status(405)
+        .header("Allow", allow)
+        .body(Vec::new())
         .unwrap()
+}
+
+/// Serves files out of a directory on disk, registered against a catch-all path like
+/// `/static/{tail}` (see [`PathMatcher`](super::PathMatcher)'s handling of a `tail` segment)
+///
+/// Built via [`static_files`] rather than constructed directly, matching the other
+/// [`Handler`](super::Handler) factories in this module.
+pub struct StaticFiles {
+    root: PathBuf
+}
+
+impl StaticFiles {
+    /// Resolve `tail` against `root`, refusing to serve anything that escapes it (e.g. via
+    /// `../` segments), and return its contents with a `Content-Type` guessed from its extension
+    fn serve(&self, req: &http::Request<Vec<u8>>, tail: &str) -> http::Response<Vec<u8>> {
+        let root = match self.root.canonicalize() {
+            Ok(root) => root,
+            Err(_) => return not_found(req),
+        };
+        let candidate = match root.join(tail).canonicalize() {
+            Ok(candidate) => candidate,
+            Err(_) => return not_found(req),
+        };
+        if !candidate.starts_with(&root) {
+            return not_found(req);
+        }
+
+        match std::fs::read(&candidate) {
+            Ok(bytes) => http::Response::builder()
+                .status(200)
+                .header("Content-Type", guess_mime_type(&candidate))
+                .body(bytes)
+                .unwrap(),
+            Err(_) => not_found(req),
+        }
+    }
+}
+
+impl Handler for StaticFiles {
+    fn call(&self, req: &http::Request<Vec<u8>>, params: Params) -> http::Response<Vec<u8>> {
+        let tail = params.get("tail").map(String::as_str).unwrap_or("");
+        self.serve(req, tail)
+    }
+}
+
+/// Creates a [`StaticFiles`] handler that serves files out of `root`, meant to be registered
+/// against a catch-all path
+///
+/// # Example
+///
+/// ```rust
+/// app.add_handler("/static/{tail}", default_handlers::static_files("public"));
+/// ```
+pub fn static_files(root: &str) -> StaticFiles {
+    StaticFiles{root: PathBuf::from(root)}
+}
+
+/// Guess a `Content-Type` from a file's extension, falling back to a generic binary type for
+/// anything unrecognized
+fn guess_mime_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") | Some("htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("txt") => "text/plain",
+        _ => "application/octet-stream"
+    }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
-mod lib;
-use lib::app::App;
+use simple_http::app::App;
+use simple_http::Params;
 
 use http::{Request, Response};
 
@@ -9,9 +9,9 @@ fn main() {
     app.run();
 }
 
-fn echo_handler(req: Request<&str>) -> Response<String> {
+fn echo_handler(req: &Request<Vec<u8>>, _params: Params) -> Response<Vec<u8>> {
     Response::builder()
         .status(200)
-        .body(lib::http_util::extract_header(req, "Host"))
+        .body(simple_http::http_util::extract_header(req, "Host").into_bytes())
         .unwrap()
 }
@@ -14,7 +14,7 @@ use std::ops::Deref;
 /// ```rust
 /// let host: String = extract_header(req, "Host");
 /// ```
-pub fn extract_header<T>(src: http::Request<T>, header: &str) -> String {
+pub fn extract_header<T>(src: &http::Request<T>, header: &str) -> String {
     let hv = src.headers().get(header).unwrap();
     let cropped = format!("{:?}", hv);
     let cropped: std::borrow::Cow<'_, str> = regex::Regex::new("(^\")|(\"$)")
@@ -44,7 +44,7 @@ pub fn parse_header(header: &http::HeaderValue) -> String {
     String::from(cropped.deref())
 }
 /// Remove percent formatting from a [`http::Request`]
-pub fn parse_query(req: http::Request<&str>) -> Option<String> {
+pub fn parse_query<T>(req: &http::Request<T>) -> Option<String> {
     match req.uri().query() {
         Some(t) => {
             let decoded_query = percent_encoding::percent_decode_str(t).decode_utf8_lossy();
@@ -54,20 +54,24 @@ pub fn parse_query(req: http::Request<&str>) -> Option<String> {
     }
 }
 
-/// Take a raw HTTP request string and create a [`http::Request`] with the relevant fields
-pub fn parse_request(req_str: &str) -> Option<http::Request<&str>> {
+/// Parse an already-assembled request line plus headers (`head`, with no trailing blank line)
+/// and attach the already-read `body` bytes, producing a [`http::Request`]
+///
+/// `head` and `body` are expected to have already been split out of the raw connection bytes by
+/// the caller (see [`crate::app`]'s read loop), since finding the `\r\n\r\n` terminator and
+/// honoring `Content-Length` both require access to the stream, not just a fixed-size buffer.
+pub fn parse_request(head: &str, body: Vec<u8>) -> Option<http::Request<Vec<u8>>> {
     let re = regex::Regex::new(concat!(
-        r"(?m)(?P<method>[A-Z]+) ",
+        r"\A(?P<method>[A-Z]+) ",
         r"(?P<path>[^ ]+) ",
         r"HTTP/1\.\d\r\n",
-        r"(?P<headers>(?:[A-Za-z-]+: [^\r\n]+\r\n)+)?",
-        r"(?:\r\n(?P<body>.+))?"
+        r"(?P<headers>(?:[A-Za-z-]+: [^\r\n]+\r\n)*)\z"
     ))
     .unwrap();
-    let caps = match re.captures(req_str) {
+    let caps = match re.captures(head) {
         Some(t) => t,
         None => {
-            println!("{}", req_str);
+            println!("{}", head);
             return Option::None;
         }
     };
@@ -85,11 +89,6 @@ pub fn parse_request(req_str: &str) -> Option<http::Request<&str>> {
         .map(|x| x.split_once(": ").unwrap())
         .collect();
 
-    let body = match caps.name("body") {
-        Option::Some(t) => t.as_str(),
-        Option::None => "",
-    };
-
     let mut build = http::Request::builder().method(method).uri(path);
     for (key, value) in headers {
         build = build.header(key, value);
@@ -97,7 +96,7 @@ pub fn parse_request(req_str: &str) -> Option<http::Request<&str>> {
     Some(build.body(body).unwrap())
 }
 
-pub fn construct_request(req: http::Request<&str>) -> String {
+pub fn construct_request(req: http::Request<Vec<u8>>) -> String {
     format!(
         "{method} {path} {version:?}\r\nHost: {host:?}\r\n{headers}\r\n{body}",
         method = req.method(),
@@ -112,6 +111,6 @@ pub fn construct_request(req: http::Request<&str>) -> String {
             )
             .collect::<Vec<String>>()
             .join("\r\n"),
-        body = req.body()
+        body = String::from_utf8_lossy(req.body())
     )
 }
\ No newline at end of file
@@ -1,18 +1,105 @@
 //! Quick and dirty HTTP library for handling simple web requests
-use http::{Request, Response};
-use regex::Regex;
+use http::{Method, Request, Response};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
 
-/// Function signature for handler functions 
-pub type RequestHandler = fn(req: Request<&str>) -> Response<String>;
+/// Function signature for handler functions
+///
+/// The request body is owned `Vec<u8>` rather than a borrowed `&str` so that large or binary
+/// payloads (assembled from a `Content-Length`-driven read loop) survive intact.
+pub type RequestHandler = fn(req: &Request<Vec<u8>>, params: Params) -> Response<Vec<u8>>;
 
-/// A struct containing a pattern for URI paths to match; TBA is a means of extracting parameters (e.g. `/users/{id}`)
+/// Anything that can handle a matched request, registered via [`app::App::add_handler`] and
+/// friends
+///
+/// A plain [`RequestHandler`] function implements this automatically. The extra trait exists so
+/// stateful handlers - like [`default_handlers::static_files`], which needs to remember a root
+/// directory - can be registered too, not just bare `fn` pointers.
+pub trait Handler: Send + Sync {
+    fn call(&self, req: &Request<Vec<u8>>, params: Params) -> Response<Vec<u8>>;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request<Vec<u8>>, Params) -> Response<Vec<u8>> + Send + Sync
+{
+    fn call(&self, req: &Request<Vec<u8>>, params: Params) -> Response<Vec<u8>> {
+        self(req, params)
+    }
+}
+
+/// Named path parameters extracted from a matched [`PathMatcher`], e.g. `{id}` in `/users/{id}`
+pub type Params = HashMap<String, String>;
+
+/// A struct containing a pattern for URI paths to match, compiled from segments like `/users/{id}`
+///
+/// A `{name}` segment becomes a named capture group that matches a single path segment
+/// (`[^/]+`). A `{name:pattern}` segment (or a segment literally named `tail`, e.g. `{tail}`)
+/// uses `pattern` (or `.*` for `tail`) instead, which allows the capture to span slashes -
+/// handy for a catch-all suffix like `/static/{tail}`.
 pub struct PathMatcher {
-    pub regex: Regex
+    pub regex: Regex,
+    pub param_names: Vec<String>
+}
+
+impl PathMatcher {
+    /// Compile a `path` pattern, translating `{name}`/`{name:pattern}` segments into named
+    /// regex capture groups
+    pub fn new(path: &str) -> PathMatcher {
+        let segment = Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*)(:[^}]+)?\}").unwrap();
+        let mut param_names = Vec::new();
+        let translated = segment.replace_all(path, |caps: &Captures| {
+            let name = caps.get(1).unwrap().as_str();
+            param_names.push(name.to_string());
+            match caps.get(2) {
+                Some(pattern) => format!("(?P<{}>{})", name, &pattern.as_str()[1..]),
+                None if name == "tail" => format!("(?P<{}>.*)", name),
+                None => format!("(?P<{}>[^/]+)", name)
+            }
+        });
+
+        PathMatcher{regex: Regex::new(&format!("\\A{}\\z", translated)).unwrap(), param_names}
+    }
 }
 
-/// A `Vec` that associates [`PathMatcher`] expressions with [`RequestHandler`] functions
-pub type HandlerList = Vec<(PathMatcher, RequestHandler)>;
+/// A `Vec` that associates a [`PathMatcher`] and an optional [`Method`] filter (`None` matches
+/// any method) with the [`Handler`] that should handle the request
+pub type HandlerList = Vec<(PathMatcher, Option<Method>, Box<dyn Handler>)>;
 
 pub mod app;
 pub mod default_handlers;
-pub mod http_util;
\ No newline at end of file
+pub mod http_util;
+pub mod middleware;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_and_extracts_a_single_named_segment() {
+        let matcher = PathMatcher::new("/users/{id}");
+        let caps = matcher.regex.captures("/users/5").unwrap();
+        assert_eq!(caps.name("id").unwrap().as_str(), "5");
+    }
+
+    #[test]
+    fn does_not_match_a_longer_or_shorter_path() {
+        let matcher = PathMatcher::new("/users/{id}");
+        assert!(matcher.regex.captures("/users/5/comments").is_none());
+        assert!(matcher.regex.captures("/api/users/5").is_none());
+        assert!(matcher.regex.captures("/users/").is_none());
+    }
+
+    #[test]
+    fn tail_segment_matches_across_slashes() {
+        let matcher = PathMatcher::new("/static/{tail}");
+        let caps = matcher.regex.captures("/static/a/b/c.png").unwrap();
+        assert_eq!(caps.name("tail").unwrap().as_str(), "a/b/c.png");
+    }
+
+    #[test]
+    fn records_param_names_in_order() {
+        let matcher = PathMatcher::new("/a/{x}/b/{y}");
+        assert_eq!(matcher.param_names, vec!["x".to_string(), "y".to_string()]);
+    }
+}
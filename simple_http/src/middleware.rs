@@ -0,0 +1,50 @@
+//! Cross-cutting logic that can run before and after every request, without editing every handler
+
+use http::{Request, Response};
+
+/// Runs around every request handled by an [`App`](super::app::App)
+///
+/// Both methods have a no-op default, so a middleware only needs to implement the hook it cares
+/// about. `before` runs in registration order ahead of the matched handler and can inspect or
+/// rewrite the request; `after` runs in reverse registration order once a response exists and
+/// can inspect or rewrite it.
+pub trait Middleware: Send + Sync {
+    /// Runs before the matched handler, with the ability to mutate the request it sees
+    fn before(&self, _req: &mut Request<Vec<u8>>) {}
+    /// Runs after the matched handler, with the ability to mutate the response before it's sent
+    fn after(&self, _req: &Request<Vec<u8>>, _res: &mut Response<Vec<u8>>) {}
+}
+
+/// Injects a fixed set of headers onto every response, mirroring actix's `middleware::DefaultHeaders`
+pub struct DefaultHeaders {
+    headers: Vec<(String, String)>
+}
+
+impl DefaultHeaders {
+    /// Creates an empty [`DefaultHeaders`] with no headers configured
+    pub fn new() -> DefaultHeaders {
+        DefaultHeaders{headers: Vec::new()}
+    }
+    /// Adds a `key: value` header to be injected into every response
+    pub fn header(mut self, key: &str, value: &str) -> DefaultHeaders {
+        self.headers.push((key.to_string(), value.to_string()));
+        self
+    }
+}
+
+impl Default for DefaultHeaders {
+    fn default() -> DefaultHeaders {
+        DefaultHeaders::new()
+    }
+}
+
+impl Middleware for DefaultHeaders {
+    fn after(&self, _req: &Request<Vec<u8>>, res: &mut Response<Vec<u8>>) {
+        for (key, value) in &self.headers {
+            res.headers_mut().insert(
+                http::HeaderName::from_bytes(key.as_bytes()).unwrap(),
+                http::HeaderValue::from_str(value).unwrap()
+            );
+        }
+    }
+}